@@ -1,12 +1,16 @@
 use anyhow::Result;
 use base64::Engine;
-use chrono::{Datelike, Days, NaiveDate, Weekday};
+use chrono::{Datelike, Days, NaiveDate, Timelike, Weekday};
 use clap::Parser;
 use itertools::Itertools;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use ureq::OrAnyStatus;
 
-use std::{collections::HashMap, env};
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
 
 // If you take more than a year of leave, we might miss it. Sorry.
 const LEAVE_LOOKAHEAD: Days = Days::new(365);
@@ -16,17 +20,68 @@ fn main() {
 
     let bamboo_company_domain = require_from_env("BAMBOO_COMPANY_DOMAIN");
     let bamboo_api_key = require_from_env("BAMBOO_API_KEY");
-    let slack_webhook_url = require_from_env("SLACK_WEBHOOK_URL");
 
-    let date = match args.date {
-        Some(date) => chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+    match &args.schedule {
+        Some(expr) => run_scheduled(&args, &bamboo_company_domain, &bamboo_api_key, expr),
+        None => {
+            let date = resolve_date(&args);
+            run_once(&args, &bamboo_company_domain, &bamboo_api_key, date).unwrap();
+        }
+    }
+}
+
+/// Resolves the report date from `--date`, falling back to today.
+fn resolve_date(args: &Args) -> NaiveDate {
+    match &args.date {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
             .expect("Invalid date argument (expected YYYY-MM-DD)"),
         None => chrono::Local::now().date_naive(),
-    };
+    }
+}
+
+/// Keeps the process running, firing `run_once` on the recurring basis described by
+/// `--schedule`, with `date` recomputed as "today" at each firing.
+fn run_scheduled(args: &Args, domain: &str, api_key: &str, expr: &str) {
+    let schedule = CronSchedule::parse(expr).expect("Invalid --schedule cron expression");
 
-    println!("sending leave for {}", date);
+    // Guards against firing twice for the same minute, e.g. if we wake up slightly early.
+    let mut last_fired: Option<chrono::NaiveDateTime> = None;
 
-    let leave = fetch_leave_from_bamboo(&bamboo_company_domain, &bamboo_api_key, date).unwrap();
+    loop {
+        let now = chrono::Local::now().naive_local();
+        let next = schedule
+            .next_after(now)
+            .expect("--schedule expression never matches any time in the next 5 years");
+
+        let wait = (next - chrono::Local::now().naive_local())
+            .to_std()
+            .unwrap_or_default();
+        std::thread::sleep(wait);
+
+        if last_fired == Some(next) {
+            continue;
+        }
+        last_fired = Some(next);
+
+        println!("schedule fired at {}, running report", next);
+
+        let date = chrono::Local::now().date_naive();
+        if let Err(e) = run_once(args, domain, api_key, date) {
+            eprintln!("report failed, will retry next firing: {:#}", e);
+        }
+    }
+}
+
+/// Runs a single fetch-and-report pass for `date`.
+fn run_once(args: &Args, domain: &str, api_key: &str, date: NaiveDate) -> Result<()> {
+    // A week digest covers the whole week from its Monday, which can fall before `date`.
+    let fetch_start = if args.output == OutputMode::Slack && args.digest == DigestMode::Week {
+        date.checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))
+            .unwrap()
+    } else {
+        date
+    };
+    let leave = fetch_leave_from_bamboo(domain, api_key, fetch_start)?;
 
     let mut time_off: Vec<TimeOff> = leave
         .iter()
@@ -36,33 +91,174 @@ fn main() {
         })
         .collect();
 
-    let leave_per_user = current_contiguous_period_per_user(&mut time_off, date);
-
-    let directory = fetch_directory_from_bamboo(&bamboo_company_domain, &bamboo_api_key).unwrap();
+    let directory = fetch_directory_from_bamboo(domain, api_key, &directory_cache_path(args))?;
     let directory: HashMap<String, EmployeeInfo> = directory
         .employees
         .into_iter()
         .map(|e| (e.id.clone(), e))
         .collect();
 
-    let mut leave_with_user_info: Vec<TimeOffWithEmployeeInfo> = leave_per_user
-        .into_iter()
-        .map(|time_off| {
-            let employee_info = directory.get(&time_off.employee_id.to_string());
-            TimeOffWithEmployeeInfo {
-                time_off,
-                employee_info,
+    match args.output {
+        OutputMode::Ical => {
+            let upcoming: Vec<TimeOff> = time_off.iter().filter(|t| t.end >= date).cloned().collect();
+            let periods = merge_contiguous_periods_per_user(&upcoming);
+
+            let periods_with_user_info: Vec<TimeOffWithEmployeeInfo> = periods
+                .into_iter()
+                .map(|time_off| {
+                    let employee_info = directory.get(&time_off.employee_id.to_string());
+                    TimeOffWithEmployeeInfo {
+                        time_off,
+                        employee_info,
+                    }
+                })
+                .collect();
+            let periods_with_user_info = filter_leave(periods_with_user_info, args);
+
+            print!("{}", render_ical(&periods_with_user_info));
+        }
+        OutputMode::Html => {
+            let time_off_with_user_info: Vec<TimeOffWithEmployeeInfo> = time_off
+                .into_iter()
+                .map(|time_off| {
+                    let employee_info = directory.get(&time_off.employee_id.to_string());
+                    TimeOffWithEmployeeInfo {
+                        time_off,
+                        employee_info,
+                    }
+                })
+                .collect();
+            let time_off_with_user_info = filter_leave(time_off_with_user_info, args);
+
+            print!(
+                "{}",
+                render_html_calendar(&time_off_with_user_info, date, args.days, args.privacy)
+            );
+        }
+        OutputMode::Slack => {
+            let slack_webhook_url = require_from_env("SLACK_WEBHOOK_URL");
+
+            match args.digest {
+                DigestMode::Day => {
+                    println!("sending leave for {}", date);
+
+                    let leave_per_user = current_contiguous_period_per_user(&mut time_off, date);
+
+                    let leave_with_user_info: Vec<TimeOffWithEmployeeInfo> = leave_per_user
+                        .into_iter()
+                        .map(|time_off| {
+                            let employee_info = directory.get(&time_off.employee_id.to_string());
+                            TimeOffWithEmployeeInfo {
+                                time_off,
+                                employee_info,
+                            }
+                        })
+                        .collect();
+                    let mut leave_with_user_info = filter_leave(leave_with_user_info, args);
+
+                    send_to_slack(&mut leave_with_user_info, slack_webhook_url, date)?;
+                }
+                DigestMode::Week => {
+                    let week_start = date
+                        .checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))
+                        .unwrap();
+
+                    println!("sending week digest for week starting {}", week_start);
+
+                    let time_off_with_user_info: Vec<TimeOffWithEmployeeInfo> = time_off
+                        .into_iter()
+                        .map(|time_off| {
+                            let employee_info = directory.get(&time_off.employee_id.to_string());
+                            TimeOffWithEmployeeInfo {
+                                time_off,
+                                employee_info,
+                            }
+                        })
+                        .collect();
+                    let time_off_with_user_info = filter_leave(time_off_with_user_info, args);
+
+                    send_week_digest_to_slack(&time_off_with_user_info, slack_webhook_url, week_start)?;
+                }
             }
-        })
-        .collect();
+        }
+    }
 
-    send_to_slack(&mut leave_with_user_info, slack_webhook_url, date).unwrap();
+    Ok(())
 }
 
 #[derive(Parser)]
 struct Args {
     #[arg(long)]
     date: Option<String>,
+
+    /// Where to send the report: post to Slack, print an iCalendar feed, or render an HTML
+    /// calendar grid, to stdout.
+    #[arg(long, value_enum, default_value_t = OutputMode::Slack)]
+    output: OutputMode,
+
+    /// Number of days (starting at `date`) to render in HTML calendar mode.
+    #[arg(long, default_value_t = 14)]
+    days: u64,
+
+    /// In HTML calendar mode, whether to show full names (Private) or first name plus last
+    /// initial (Public), so the file can be published without exposing full rosters.
+    #[arg(long, value_enum, default_value_t = CalendarPrivacy::Private)]
+    privacy: CalendarPrivacy,
+
+    /// Only include leave for employees in this department (repeatable, case-insensitive
+    /// substring match).
+    #[arg(long = "department")]
+    departments: Vec<String>,
+
+    /// Exclude leave for employees in this department (repeatable, case-insensitive substring
+    /// match).
+    #[arg(long = "exclude-department")]
+    exclude_departments: Vec<String>,
+
+    /// Only include leave for this employee, matched by id or by name (repeatable,
+    /// case-insensitive substring match on name).
+    #[arg(long = "employee")]
+    employees: Vec<String>,
+
+    /// Exclude leave for this employee, matched by id or by name (repeatable, case-insensitive
+    /// substring match on name).
+    #[arg(long = "exclude-employee")]
+    exclude_employees: Vec<String>,
+
+    /// Keep running as a daemon, firing the report on the recurring basis described by this
+    /// standard 5-field cron expression (minute hour day-of-month month day-of-week), instead
+    /// of running once and exiting.
+    #[arg(long)]
+    schedule: Option<String>,
+
+    /// In Slack output mode, post a single day's leave (Day) or a whole week's look-ahead
+    /// digest (Week), anchored to the Monday of the week containing `date`.
+    #[arg(long, value_enum, default_value_t = DigestMode::Day)]
+    digest: DigestMode,
+
+    /// Where to cache the employee directory response for conditional requests. Defaults to a
+    /// file under the system cache dir.
+    #[arg(long)]
+    directory_cache_path: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputMode {
+    Slack,
+    Ical,
+    Html,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DigestMode {
+    Day,
+    Week,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CalendarPrivacy {
+    Public,
+    Private,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -112,6 +308,16 @@ impl TimeOffWithEmployeeInfo<'_> {
     fn display_name(&self) -> String {
         format!("{} {}", self.first_display_name(), self.last_display_name())
     }
+
+    /// A redacted display name (first name + last initial) for publishing somewhere that
+    /// shouldn't expose the full roster.
+    fn public_display_name(&self) -> String {
+        let last_initial = self.last_display_name().chars().next();
+        match last_initial {
+            Some(initial) => format!("{} {}.", self.first_display_name(), initial),
+            None => self.first_display_name(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -178,20 +384,100 @@ struct EmployeeInfo {
     department: Option<String>,
 }
 
-fn fetch_directory_from_bamboo(domain: &str, api_key: &str) -> Result<Directory> {
+/// Fetches the employee directory, which changes rarely, using a conditional request against
+/// `cache_path` so an unchanged directory costs a `304` instead of a full re-download.
+fn fetch_directory_from_bamboo(domain: &str, api_key: &str, cache_path: &Path) -> Result<Directory> {
     let url = format!(
         "https://api.bamboohr.com/api/gateway.php/{}/v1/employees/directory/",
         domain
     );
-    let directory = ureq::get(url.as_str())
+
+    let cache = DirectoryCache::load(cache_path);
+
+    let mut request = ureq::get(url.as_str())
         .set("Accept", "application/json")
-        .set("Authorization", &basic_auth_header(api_key, "x"))
-        .call()?
-        .into_json::<Directory>()?;
+        .set("Authorization", &basic_auth_header(api_key, "x"));
+
+    if let Some(cache) = &cache {
+        if let Some(etag) = &cache.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &cache.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+    }
+
+    let resp = request.call().or_any_status()?;
+
+    if resp.status() == 304 {
+        return match cache {
+            Some(cache) => Ok(serde_json::from_str(&cache.body)?),
+            None => Err(anyhow::format_err!(
+                "BambooHR returned 304 Not Modified but there is no cached directory at {}",
+                cache_path.display()
+            )),
+        };
+    }
+
+    if resp.status() >= 400 {
+        return Err(anyhow::format_err!(
+            "request to BambooHR directory API failed (status {})",
+            resp.status()
+        ));
+    }
+
+    let etag = resp.header("ETag").map(str::to_string);
+    let last_modified = resp.header("Last-Modified").map(str::to_string);
+    let body = resp.into_string()?;
+    let directory: Directory = serde_json::from_str(&body)?;
+
+    DirectoryCache {
+        etag,
+        last_modified,
+        body,
+    }
+    .save(cache_path);
 
     Ok(directory)
 }
 
+/// The cached employee directory response body, alongside the headers needed to make a
+/// conditional request for it next time.
+#[derive(Serialize, Deserialize)]
+struct DirectoryCache {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl DirectoryCache {
+    fn load(path: &Path) -> Option<DirectoryCache> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn save(&self, path: &Path) {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Ok(contents) = serde_json::to_string(self) {
+            let _ = std::fs::write(path, contents);
+        }
+    }
+}
+
+/// Resolves where the directory cache lives: `--directory-cache-path` if given, otherwise
+/// under the system cache dir.
+fn directory_cache_path(args: &Args) -> PathBuf {
+    match &args.directory_cache_path {
+        Some(path) => PathBuf::from(path),
+        None => dirs::cache_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("bamboo-leave-to-slack")
+            .join("directory-cache.json"),
+    }
+}
+
 /// Returns the first contiguous period of leave for each user (grouping by name).
 ///
 /// Leave periods are adjacent if they:
@@ -220,6 +506,41 @@ fn current_contiguous_period_per_user(leave: &mut [TimeOff], date: NaiveDate) ->
         .collect_vec()
 }
 
+/// Merges every contiguous period of leave per user across the whole fetched window, rather
+/// than just the first one. Used for feeds (e.g. iCal) that cover more than a single day.
+fn merge_contiguous_periods_per_user(leave: &[TimeOff]) -> Vec<TimeOff> {
+    let mut by_employee: HashMap<usize, Vec<TimeOff>> = HashMap::new();
+    for period in leave {
+        by_employee
+            .entry(period.employee_id)
+            .or_default()
+            .push(period.clone());
+    }
+
+    let mut merged: Vec<TimeOff> = Vec::new();
+
+    for (_, mut periods) in by_employee {
+        periods.sort_by(|a, b| a.start.cmp(&b.start).then(a.end.cmp(&b.end)));
+
+        for period in periods {
+            match merged.last_mut() {
+                Some(last)
+                    if last.employee_id == period.employee_id
+                        && (period.start <= last.end
+                            || same_or_adjacent_workdays(last.end, period.start)) =>
+                {
+                    if period.end > last.end {
+                        last.end = period.end;
+                    }
+                }
+                _ => merged.push(period),
+            }
+        }
+    }
+
+    merged
+}
+
 /// Returns true if dates are the same, are adjacent, or if they are separated by a weekend.
 fn same_or_adjacent_workdays(a: NaiveDate, b: NaiveDate) -> bool {
     let (a, b) = if a <= b { (a, b) } else { (b, a) };
@@ -230,6 +551,70 @@ fn same_or_adjacent_workdays(a: NaiveDate, b: NaiveDate) -> bool {
     // Crossing a weekend
 }
 
+/// Scopes a report to the departments/employees requested on the command line, so one
+/// scheduled invocation can post only (say) Engineering's leave to a team's own channel.
+fn filter_leave<'a>(
+    mut time_off: Vec<TimeOffWithEmployeeInfo<'a>>,
+    args: &Args,
+) -> Vec<TimeOffWithEmployeeInfo<'a>> {
+    time_off.retain(|t| {
+        let department = t
+            .employee_info
+            .and_then(|e| e.department.as_deref())
+            .unwrap_or("");
+
+        if !args.departments.is_empty()
+            && !args.departments.iter().any(|d| contains_ignore_case(department, d))
+        {
+            return false;
+        }
+
+        if args
+            .exclude_departments
+            .iter()
+            .any(|d| contains_ignore_case(department, d))
+        {
+            return false;
+        }
+
+        let employee_id = t.time_off.employee_id.to_string();
+        let display_name = t.display_name();
+        let matches_employee = |e: &str| employee_id == e || contains_ignore_case(&display_name, e);
+
+        if !args.employees.is_empty() && !args.employees.iter().any(|e| matches_employee(e)) {
+            return false;
+        }
+
+        if args.exclude_employees.iter().any(|e| matches_employee(e)) {
+            return false;
+        }
+
+        true
+    });
+
+    time_off
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Groups items by department, sorted by department name (with `None` - i.e. "Other
+/// departments" - sorting first). Shared by the Slack message and the HTML calendar.
+fn group_by_department<T>(
+    items: Vec<T>,
+    department_of: impl Fn(&T) -> Option<String>,
+) -> Vec<(Option<String>, Vec<T>)> {
+    let mut grouped: Vec<(Option<String>, Vec<T>)> = items
+        .into_iter()
+        .into_group_map_by(department_of)
+        .into_iter()
+        .collect();
+
+    grouped.sort_by_key(|(department, _)| department.clone());
+    grouped
+}
+
 fn send_to_slack(
     time_off: &mut [TimeOffWithEmployeeInfo],
     url: String,
@@ -239,14 +624,9 @@ fn send_to_slack(
 
     let mut message_blocks: Vec<serde_json::Value> = Vec::new();
 
-    let mut time_off_by_department: Vec<(Option<String>, Vec<&mut TimeOffWithEmployeeInfo>)> =
-        time_off
-            .iter_mut()
-            .into_group_map_by(|t| t.employee_info.and_then(|e| e.department.clone()))
-            .into_iter()
-            .collect();
-
-    time_off_by_department.sort_by_key(|(department, _)| department.clone());
+    let time_off_by_department = group_by_department(time_off.iter_mut().collect(), |t| {
+        t.employee_info.and_then(|e| e.department.clone())
+    });
 
     let time_off: Vec<Vec<serde_json::Value>> = time_off_by_department
     .into_iter()
@@ -349,6 +729,111 @@ fn send_to_slack(
         ))
     }
 
+    post_blocks_to_slack(message_blocks, url)
+}
+
+/// Posts a whole week's look-ahead leave as a Slack message, with one section header per day
+/// that has anyone out (days with nobody out are skipped), grouped by department the same way
+/// the single-day message is.
+fn send_week_digest_to_slack(
+    time_off: &[TimeOffWithEmployeeInfo],
+    url: String,
+    week_start: NaiveDate,
+) -> Result<()> {
+    post_blocks_to_slack(build_week_digest_blocks(time_off, week_start), url)
+}
+
+/// Builds the Slack blocks for `send_week_digest_to_slack`, split out so the day-by-day
+/// grouping and sorting can be tested without making a network call.
+fn build_week_digest_blocks(
+    time_off: &[TimeOffWithEmployeeInfo],
+    week_start: NaiveDate,
+) -> Vec<serde_json::Value> {
+    let mut message_blocks: Vec<serde_json::Value> = vec![ureq::json!({
+        "type": "header",
+        "text": {
+            "type": "plain_text",
+            "text": ":wave: This week's leave",
+            "emoji": true
+        }
+    })];
+
+    for offset in 0..7 {
+        let day = week_start.checked_add_days(Days::new(offset)).unwrap();
+
+        let mut on_leave: Vec<&TimeOffWithEmployeeInfo> = time_off
+            .iter()
+            .filter(|t| t.time_off.includes(day))
+            .collect();
+
+        if on_leave.is_empty() {
+            continue;
+        }
+
+        on_leave.sort_unstable_by_key(|t| t.display_name());
+
+        message_blocks.push(ureq::json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!("*{}*", day.format("%A %-d %B"))
+            }
+        }));
+
+        let by_department = group_by_department(on_leave, |t| {
+            t.employee_info.and_then(|e| e.department.clone())
+        });
+
+        for (department, people) in by_department {
+            let list_elements: Vec<serde_json::Value> = people
+                .into_iter()
+                .map(|t| {
+                    ureq::json!({
+                        "type": "rich_text_section",
+                        "elements": [{
+                            "type": "text",
+                            "text": t.display_name(),
+                            "style": { "bold": true }
+                        }]
+                    })
+                })
+                .collect();
+
+            message_blocks.push(ureq::json!({
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!("_{}_", department.unwrap_or("Other departments".to_string()))
+                }
+            }));
+
+            message_blocks.push(ureq::json!({
+                "type": "rich_text",
+                "elements": [{
+                    "type": "rich_text_list",
+                    "style": "bullet",
+                    "elements": list_elements,
+                }]
+            }));
+        }
+    }
+
+    if message_blocks.len() == 1 {
+        message_blocks.push(ureq::json!(
+            {
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": "*Nobody is on leave this week*",
+                }
+            }
+        ))
+    }
+
+    message_blocks
+}
+
+fn post_blocks_to_slack(message_blocks: Vec<serde_json::Value>, url: String) -> Result<()> {
     let message = ureq::json!({
         "blocks": message_blocks,
     });
@@ -368,6 +853,300 @@ fn send_to_slack(
     Ok(())
 }
 
+/// Renders merged leave periods as an RFC 5545 VCALENDAR, with one all-day VEVENT per
+/// contiguous period, so the output can be redirected to a file and subscribed to.
+fn render_ical(time_off: &[TimeOffWithEmployeeInfo]) -> String {
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//bamboo-leave-to-slack//EN".to_string(),
+    ];
+
+    // RFC 5545 requires DTSTAMP on every VEVENT; it's the time this feed was generated, not
+    // the leave dates, so it's the same for every event in one render.
+    let dtstamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    for t in time_off {
+        // iCal all-day DTEND is exclusive, so the event covers up to and including `end`.
+        let dtend = t.time_off.end.checked_add_days(Days::new(1)).unwrap();
+        let uid = format!(
+            "{}-{}@bamboo-leave-to-slack",
+            t.time_off.employee_id,
+            t.time_off.start.format("%Y%m%d")
+        );
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", uid));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        lines.push(format!("SUMMARY:{} on leave", t.display_name()));
+        lines.push(format!(
+            "DTSTART;VALUE=DATE:{}",
+            t.time_off.start.format("%Y%m%d")
+        ));
+        lines.push(format!("DTEND;VALUE=DATE:{}", dtend.format("%Y%m%d")));
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|l| fold_ics_line(l)).join("\r\n") + "\r\n"
+}
+
+/// Folds a single iCalendar content line at 75 octets, as required by RFC 5545: continuation
+/// lines start with a single space.
+fn fold_ics_line(line: &str) -> String {
+    if line.len() <= 75 {
+        return line.to_string();
+    }
+
+    let mut folded = String::new();
+    let mut start = 0;
+    let mut first = true;
+
+    while start < line.len() {
+        let limit = if first { 75 } else { 74 };
+        let mut end = (start + limit).min(line.len());
+        while end > start && !line.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        if !first {
+            folded.push('\r');
+            folded.push('\n');
+            folded.push(' ');
+        }
+        folded.push_str(&line[start..end]);
+
+        start = end;
+        first = false;
+    }
+
+    folded
+}
+
+/// Renders a rolling `days`-day "who's out" calendar as a self-contained HTML document, one
+/// row per day, with names grouped by department the same way the Slack message groups them.
+fn render_html_calendar(
+    time_off: &[TimeOffWithEmployeeInfo],
+    date: NaiveDate,
+    days: u64,
+    privacy: CalendarPrivacy,
+) -> String {
+    let mut html = String::new();
+    html.push_str("<!doctype html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Who's out</title>\n<style>\n");
+    html.push_str("table { border-collapse: collapse; }\n");
+    html.push_str("td, th { border: 1px solid #ccc; padding: 4px 8px; vertical-align: top; text-align: left; }\n");
+    html.push_str("</style>\n</head>\n<body>\n<table>\n");
+
+    for offset in 0..days {
+        let day = date.checked_add_days(Days::new(offset)).unwrap();
+
+        let mut on_leave: Vec<&TimeOffWithEmployeeInfo> = time_off
+            .iter()
+            .filter(|t| t.time_off.includes(day))
+            .collect();
+        on_leave.sort_unstable_by_key(|t| match privacy {
+            CalendarPrivacy::Private => t.display_name(),
+            CalendarPrivacy::Public => t.public_display_name(),
+        });
+
+        let by_department = group_by_department(on_leave, |t| {
+            t.employee_info.and_then(|e| e.department.clone())
+        });
+
+        html.push_str("<tr>\n");
+        html.push_str(&format!("<th>{}</th>\n<td>\n", day.format("%A %-d %B")));
+
+        for (department, people) in by_department {
+            let department = department.unwrap_or_else(|| "Other departments".to_string());
+            let names = people
+                .iter()
+                .map(|t| {
+                    let name = match privacy {
+                        CalendarPrivacy::Private => t.display_name(),
+                        CalendarPrivacy::Public => t.public_display_name(),
+                    };
+                    format!("<b>{}</b>", html_escape(&name))
+                })
+                .join(", ");
+
+            html.push_str(&format!(
+                "<p><em>{}</em><br>\n{}</p>\n",
+                html_escape(&department),
+                names
+            ));
+        }
+
+        html.push_str("</td>\n</tr>\n");
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn html_escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A standard 5-field cron expression (minute hour day-of-month month day-of-week), used by
+/// `--schedule` to drive the daemon mode.
+struct CronSchedule {
+    minute: CronField,
+    hour: CronField,
+    day_of_month: CronField,
+    month: CronField,
+    day_of_week: CronField,
+}
+
+impl CronSchedule {
+    fn parse(expr: &str) -> Result<CronSchedule> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow::format_err!(
+                "cron expression must have 5 fields (minute hour day-of-month month day-of-week), got {}",
+                fields.len()
+            ));
+        }
+
+        Ok(CronSchedule {
+            minute: CronField::parse(fields[0], 0, 59)?,
+            hour: CronField::parse(fields[1], 0, 23)?,
+            day_of_month: CronField::parse(fields[2], 1, 31)?,
+            month: CronField::parse(fields[3], 1, 12)?,
+            day_of_week: CronField::parse(fields[4], 0, 6)?,
+        })
+    }
+
+    /// Matches `dt` against this schedule. Follows standard (vixie) cron semantics: if *both*
+    /// day-of-month and day-of-week are restricted (not `*`), the day matches when *either*
+    /// field matches, not their intersection - e.g. `0 0 1 * 1` fires at midnight on the 1st
+    /// of the month *and* every Monday, not only when the 1st is a Monday.
+    fn matches(&self, dt: chrono::NaiveDateTime) -> bool {
+        if !(self.minute.matches(dt.minute())
+            && self.hour.matches(dt.hour())
+            && self.month.matches(dt.month()))
+        {
+            return false;
+        }
+
+        let day_of_month_matches = self.day_of_month.matches(dt.day());
+        let day_of_week_matches = self
+            .day_of_week
+            .matches(dt.weekday().num_days_from_sunday());
+
+        if self.day_of_month.is_wildcard || self.day_of_week.is_wildcard {
+            day_of_month_matches && day_of_week_matches
+        } else {
+            day_of_month_matches || day_of_week_matches
+        }
+    }
+
+    /// Finds the next minute-aligned time after `after` that matches this schedule, by
+    /// incrementing a candidate minute-by-minute and testing each field. Bails out rather than
+    /// scanning forever if the schedule can never match (e.g. `day-of-month=31` combined with a
+    /// fixed 30-day `month`).
+    fn next_after(&self, after: chrono::NaiveDateTime) -> Result<chrono::NaiveDateTime> {
+        // 5 years of minutes: comfortably longer than any leap-year cycle a valid schedule
+        // needs, short enough to fail fast on a combination that can never be satisfied.
+        const MAX_MINUTES_TO_SCAN: i64 = 5 * 365 * 24 * 60;
+
+        let mut candidate = after
+            .with_second(0)
+            .unwrap()
+            .with_nanosecond(0)
+            .unwrap()
+            + chrono::Duration::minutes(1);
+
+        for _ in 0..MAX_MINUTES_TO_SCAN {
+            if self.matches(candidate) {
+                return Ok(candidate);
+            }
+            candidate += chrono::Duration::minutes(1);
+        }
+
+        Err(anyhow::format_err!(
+            "--schedule expression never matches any time in the next 5 years"
+        ))
+    }
+}
+
+/// One field of a cron expression: a comma-separated list of `*`, `a-b` ranges and `*/n` steps.
+struct CronField {
+    values: Vec<u32>,
+    is_wildcard: bool,
+}
+
+impl CronField {
+    fn parse(field: &str, min: u32, max: u32) -> Result<CronField> {
+        let mut values = Vec::new();
+
+        for part in field.split(',') {
+            if part == "*" {
+                values.extend(min..=max);
+            } else if let Some(step) = part.strip_prefix("*/") {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| anyhow::format_err!("invalid cron step: {}", part))?;
+                values.extend((min..=max).step_by(step.max(1) as usize));
+            } else if let Some((start, end)) = part.split_once('-') {
+                let start = Self::parse_value(start, min, max, part)?;
+                let end = Self::parse_value(end, min, max, part)?;
+                if start > end {
+                    return Err(anyhow::format_err!("invalid cron range: {}", part));
+                }
+                values.extend(start..=end);
+            } else {
+                values.push(Self::parse_value(part, min, max, part)?);
+            }
+        }
+
+        values.sort_unstable();
+        values.dedup();
+
+        if values.is_empty() {
+            return Err(anyhow::format_err!(
+                "cron field '{}' matches no values in range {}-{}",
+                field,
+                min,
+                max
+            ));
+        }
+
+        Ok(CronField {
+            values,
+            is_wildcard: field == "*",
+        })
+    }
+
+    /// Parses a single cron value and checks it falls within `min..=max`, rather than silently
+    /// dropping out-of-range values (a typo like `32` for day-of-month should be a hard error).
+    fn parse_value(raw: &str, min: u32, max: u32, part: &str) -> Result<u32> {
+        let value: u32 = raw
+            .parse()
+            .map_err(|_| anyhow::format_err!("invalid cron value: {}", part))?;
+
+        if value < min || value > max {
+            return Err(anyhow::format_err!(
+                "cron value {} out of range {}-{} in '{}'",
+                value,
+                min,
+                max,
+                part
+            ));
+        }
+
+        Ok(value)
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.values.contains(&value)
+    }
+}
+
 fn require_from_env(key: &str) -> String {
     env::var(key).unwrap_or_else(|_| panic!("missing required environment variable: {}", key))
 }
@@ -378,3 +1157,135 @@ fn basic_auth_header(username: &str, password: &str) -> String {
             .encode(format!("{}:{}", username, password))
             .as_str()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_field_rejects_out_of_range_values() {
+        assert!(CronField::parse("32", 1, 31).is_err());
+        assert!(CronField::parse("0-5", 1, 31).is_err());
+        assert!(CronField::parse("1-31", 1, 31).is_ok());
+    }
+
+    #[test]
+    fn cron_schedule_next_after_bails_out_on_an_impossible_schedule() {
+        // The 31st never falls in April, so this can never match.
+        let schedule = CronSchedule::parse("0 0 31 4 *").unwrap();
+        let now = NaiveDate::from_ymd_opt(2026, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        assert!(schedule.next_after(now).is_err());
+    }
+
+    #[test]
+    fn cron_schedule_next_after_finds_the_next_match() {
+        let schedule = CronSchedule::parse("30 9 * * *").unwrap();
+        let now = NaiveDate::from_ymd_opt(2026, 1, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+
+        let next = schedule.next_after(now).unwrap();
+        assert_eq!(next.hour(), 9);
+        assert_eq!(next.minute(), 30);
+    }
+
+    #[test]
+    fn cron_schedule_ors_day_of_month_and_day_of_week_when_both_restricted() {
+        // "midnight on the 1st, and every Monday" - the 2nd of Feb 2026 is a Monday but not
+        // the 1st, so it should still match under standard cron's OR rule.
+        let schedule = CronSchedule::parse("0 0 1 * 1").unwrap();
+        let monday_not_first = NaiveDate::from_ymd_opt(2026, 2, 2)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let first_not_monday = NaiveDate::from_ymd_opt(2026, 4, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let neither = NaiveDate::from_ymd_opt(2026, 4, 2)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        assert!(schedule.matches(monday_not_first));
+        assert!(schedule.matches(first_not_monday));
+        assert!(!schedule.matches(neither));
+    }
+
+    #[test]
+    fn fold_ics_line_stays_under_75_octets_per_line() {
+        let line = "SUMMARY:".to_string() + &"x".repeat(200);
+        let folded = fold_ics_line(&line);
+
+        for l in folded.split("\r\n") {
+            assert!(l.len() <= 75);
+        }
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn fold_ics_line_leaves_short_lines_untouched() {
+        assert_eq!(fold_ics_line("SUMMARY:short"), "SUMMARY:short");
+    }
+
+    #[test]
+    fn render_html_calendar_sorts_leave_by_display_name_within_a_department() {
+        let day = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let time_off: Vec<TimeOffWithEmployeeInfo> = vec!["Zoe Adams", "Amy Carter", "Mo Baker"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| TimeOffWithEmployeeInfo {
+                time_off: TimeOff {
+                    employee_id: i,
+                    name: name.to_string(),
+                    start: day,
+                    end: day,
+                },
+                employee_info: None,
+            })
+            .collect();
+
+        let html = render_html_calendar(&time_off, day, 1, CalendarPrivacy::Private);
+
+        let positions: Vec<usize> = ["Amy Carter", "Mo Baker", "Zoe Adams"]
+            .iter()
+            .map(|name| html.find(name).unwrap())
+            .collect();
+
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn build_week_digest_blocks_sorts_leave_by_display_name_within_a_department() {
+        let week_start = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let time_off: Vec<TimeOffWithEmployeeInfo> = vec!["Zoe Adams", "Amy Carter", "Mo Baker"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| TimeOffWithEmployeeInfo {
+                time_off: TimeOff {
+                    employee_id: i,
+                    name: name.to_string(),
+                    start: week_start,
+                    end: week_start,
+                },
+                employee_info: None,
+            })
+            .collect();
+
+        let blocks = build_week_digest_blocks(&time_off, week_start);
+
+        let names: Vec<&str> = blocks
+            .iter()
+            .filter(|b| b["type"] == "rich_text")
+            .flat_map(|b| b["elements"][0]["elements"].as_array().unwrap())
+            .map(|section| section["elements"][0]["text"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(names, vec!["Amy Carter", "Mo Baker", "Zoe Adams"]);
+    }
+}